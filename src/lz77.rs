@@ -1,4 +1,5 @@
 use std::cmp;
+use std::ptr;
 
 use huffman_table;
 use chained_hash_table::{WINDOW_SIZE, ChainedHashTable};
@@ -54,6 +55,65 @@ fn slide_buffer(buffer: &mut [u8], data: &[u8]) {
     upper[..data.len()].copy_from_slice(data);
 }
 
+/// Extra slack capacity kept at the end of an `InputBuffer` past the two windows it holds, so
+/// `add_data` can take in a little more than exactly two windows' worth of data in one call
+/// before the caller needs to process/slide to make room for more.
+const BUFFER_OVERLAP: usize = MAX_MATCH;
+
+/// An owned buffer of input data used for streaming compression.
+///
+/// Unlike `lz77_compress_block`, which requires the entire input up front, this lets data be
+/// fed in through repeated calls to `add_data`, so a caller doesn't need to have the whole
+/// input available (or in memory) at once.
+pub struct InputBuffer {
+    buffer: Vec<u8>,
+    // The number of bytes currently held in `buffer`.
+    current_end: usize,
+}
+
+impl InputBuffer {
+    pub fn empty() -> InputBuffer {
+        InputBuffer {
+            buffer: vec![0; (WINDOW_SIZE * 2) + BUFFER_OVERLAP],
+            current_end: 0,
+        }
+    }
+
+    /// The bytes currently held in the buffer.
+    pub fn get_buffer(&self) -> &[u8] {
+        &self.buffer[..self.current_end]
+    }
+
+    pub fn current_end(&self) -> usize {
+        self.current_end
+    }
+
+    /// Add as much of `data` as there is free space for in the buffer, returning the number
+    /// of bytes that were actually consumed. Call `slide` first to make more room if needed.
+    pub fn add_data(&mut self, data: &[u8]) -> usize {
+        let free_space = self.buffer.len() - self.current_end;
+        let to_add = cmp::min(free_space, data.len());
+        self.buffer[self.current_end..self.current_end + to_add].copy_from_slice(&data[..to_add]);
+        self.current_end += to_add;
+        to_add
+    }
+
+    /// Slide the buffer back by `window_size`, discarding everything before that point and
+    /// making room at the end for a new window's worth of data.
+    pub fn slide(&mut self, window_size: usize) {
+        let kept = self.current_end - window_size;
+        // `kept` can be larger than `window_size` (there may be up to `BUFFER_OVERLAP` extra
+        // bytes buffered ahead of the current window), so the source and destination ranges
+        // can overlap and we can't just split the buffer in half and copy between the two
+        // halves like `slide_buffer` does.
+        unsafe {
+            let ptr = self.buffer.as_mut_ptr();
+            ptr::copy(ptr.add(window_size), ptr, kept);
+        }
+        self.current_end = kept;
+    }
+}
+
 /// A structure representing values in a compressed stream of data before being huffman coded
 /// We might want to represent this differently eventually to save on memory usage
 /// (We don't actually need the full 16 bytes to store the length and distance data)
@@ -83,11 +143,64 @@ impl LDPair {
     }
 }
 
+/// Reads a `u64` from the start of `data` without requiring it to be aligned.
+///
+/// # Safety
+/// `data` must be at least 8 bytes long.
+#[inline(always)]
+unsafe fn read_u64(data: &[u8]) -> u64 {
+    let mut value: u64 = 0;
+    ptr::copy_nonoverlapping(data.as_ptr(), &mut value as *mut u64 as *mut u8, 8);
+    value
+}
+
+/// Returns the index of the first byte (0-7) in which `diff`, the XOR of two words, differs.
+#[inline(always)]
+fn first_mismatch_byte(diff: u64) -> usize {
+    if cfg!(target_endian = "little") {
+        (diff.trailing_zeros() / 8) as usize
+    } else {
+        (diff.leading_zeros() / 8) as usize
+    }
+}
+
 /// Get the length of the checked match
 /// The function returns number of bytes after and including `current_pos` match
 /// Preventing this from being inlined seems to improve performance slightly
+///
+/// `pos_to_check` must be less than `current_pos`, so that reading 8 bytes at a time starting
+/// at `pos_to_check` never reads further into `data` than the same read starting at
+/// `current_pos`, which the loop keeps within the end of `data`.
 #[inline(never)]
 fn get_match_length(data: &[u8], current_pos: usize, pos_to_check: usize) -> usize {
+    let max_length = cmp::min(data.len() - current_pos, MAX_MATCH);
+
+    let mut matched = 0;
+    // Compare 8 bytes at a time, as this is the hot loop of the whole encoder, only falling
+    // back to comparing byte-by-byte once fewer than 8 bytes of budget remain.
+    while matched + 8 <= max_length {
+        // Safe as `matched + 8 <= max_length <= data.len() - current_pos`, and
+        // `pos_to_check < current_pos`.
+        let a = unsafe { read_u64(&data[current_pos + matched..]) };
+        let b = unsafe { read_u64(&data[pos_to_check + matched..]) };
+        let diff = a ^ b;
+        if diff != 0 {
+            return matched + first_mismatch_byte(diff);
+        }
+        matched += 8;
+    }
+
+    while matched < max_length && data[current_pos + matched] == data[pos_to_check + matched] {
+        matched += 1;
+    }
+
+    matched
+}
+
+/// Safe, byte-by-byte version of `get_match_length`, kept around so the word-at-a-time
+/// version above can be checked against it in tests.
+#[cfg(test)]
+fn get_match_length_fallback(data: &[u8], current_pos: usize, pos_to_check: usize) -> usize {
     data[current_pos..]
         .iter()
         .zip(data[pos_to_check..].iter())
@@ -102,7 +215,8 @@ fn get_match_length(data: &[u8], current_pos: usize, pos_to_check: usize) -> usi
 fn longest_match(data: &[u8],
                  hash_table: &ChainedHashTable,
                  position: usize,
-                 prev_length: usize)
+                 prev_length: usize,
+                 max_hash_checks: u16)
                  -> (usize, usize) {
 
     // If we are at the start, or we already have a match at the maximum length, we stop here.
@@ -136,8 +250,8 @@ fn longest_match(data: &[u8],
 
     let mut iters = 0;
 
-    // We limit the chain length to 4096 for now to avoid taking too long
-    while current_head >= limit && current_head != 0 && iters <= 4096 {
+    // We limit the chain length to `max_hash_checks` to avoid taking too long
+    while current_head >= limit && current_head != 0 && iters < max_hash_checks {
 
         // We only check further if the match length can actually increase
         if data[position + best_length] == data[current_head + best_length] {
@@ -175,21 +289,116 @@ fn longest_match_current(data: &[u8], hash_table: &ChainedHashTable) -> (usize,
     longest_match(data,
                   hash_table,
                   hash_table.current_position(),
-                  MIN_MATCH as usize - 1)
+                  MIN_MATCH as usize - 1,
+                  4096)
 }
 
 const DEFAULT_WINDOW_SIZE: usize = 32768;
 
-// fn add_value<RC: RollingChecksum>(hash_table: &mut ChainedHashTable, rolling_checksum: RC) {
-// hash_table.
-// }
+/// The matching strategy used when looking for length/distance pairs.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MatchingType {
+    /// Emit a match as soon as one of at least `MIN_MATCH` is found, without checking whether
+    /// the next position would yield a better one. Faster, but compresses somewhat worse.
+    Greedy,
+    /// Defer the current match by one byte to check whether the next position gives a longer
+    /// one before emitting. This is the default, and usually gives better compression.
+    Lazy,
+}
+
+/// Knobs controlling how much effort is spent looking for matches, similarly to the
+/// `compression level` concept used by zlib and other deflate implementations.
+#[derive(Debug, Copy, Clone)]
+pub struct CompressionOptions {
+    /// The maximum number of checks to make in the hash chain before giving up on finding a
+    /// better match at a given position. A value of `0` together with `MatchingType::Greedy`
+    /// selects a run-length-only mode that doesn't use the hash chain at all.
+    pub max_hash_checks: u16,
+    /// If we already have a match at least this long, we don't bother checking the next
+    /// position for a possibly longer one when using lazy matching.
+    pub lazy_if_less_than: u16,
+    /// Whether to use greedy or lazy matching.
+    pub matching_type: MatchingType,
+}
+
+impl CompressionOptions {
+    pub fn new(matching_type: MatchingType,
+               max_hash_checks: u16,
+               lazy_if_less_than: u16)
+               -> CompressionOptions {
+        CompressionOptions {
+            matching_type: matching_type,
+            max_hash_checks: max_hash_checks,
+            lazy_if_less_than: lazy_if_less_than,
+        }
+    }
+}
+
+impl Default for CompressionOptions {
+    /// Roughly corresponds to the default compression level in zlib.
+    fn default() -> CompressionOptions {
+        CompressionOptions {
+            max_hash_checks: 4096,
+            lazy_if_less_than: 128,
+            matching_type: MatchingType::Lazy,
+        }
+    }
+}
 
 fn process_chunk<W: OutputWriter, RC: RollingChecksum>(data: &[u8],
                                                        start: usize,
                                                        end: usize,
                                                        hash_table: &mut ChainedHashTable,
                                                        writer: &mut W,
-                                                       _rolling_checksum: &mut RC) {
+                                                       rolling_checksum: &mut RC,
+                                                       options: &CompressionOptions) {
+    match options.matching_type {
+        MatchingType::Lazy => {
+            process_chunk_lazy(data, start, end, hash_table, writer, rolling_checksum, options)
+        }
+        MatchingType::Greedy => {
+            if options.max_hash_checks == 0 {
+                // A `max_hash_checks` of 0 selects run-length-only matching, which doesn't
+                // need the hash chain at all.
+                process_chunk_greedy_rle(data, start, end, writer, rolling_checksum)
+            } else {
+                process_chunk_greedy(data, start, end, hash_table, writer, rolling_checksum, options)
+            }
+        }
+    }
+}
+
+/// Advance `taker`/`hash_taker` (the tails of the insert/hash iterators a chunk function takes
+/// after finding a match) over the bytes a match jumps past, adding each one to the hash table
+/// and checksum, and to `on_skipped` besides (used to also write them out as literals, for the
+/// skip-ahead acceleration, where the jumped-over bytes aren't part of the match itself).
+fn advance_match<'a, I, H, RC, F>(taker: I,
+                                  mut hash_taker: H,
+                                  start: usize,
+                                  hash_table: &mut ChainedHashTable,
+                                  rolling_checksum: &mut RC,
+                                  mut on_skipped: F)
+    where I: Iterator<Item = (usize, &'a u8)>,
+          H: Iterator<Item = &'a u8>,
+          RC: RollingChecksum,
+          F: FnMut(u8)
+{
+    for (ipos, &skipped_byte) in taker {
+        rolling_checksum.update(skipped_byte);
+        on_skipped(skipped_byte);
+        if let Some(&i_hash_byte) = hash_taker.next() {
+            hash_table.add_hash_value(ipos + start, i_hash_byte);
+        }
+    }
+}
+
+fn process_chunk_lazy<W: OutputWriter, RC: RollingChecksum>(data: &[u8],
+                                                            start: usize,
+                                                            end: usize,
+                                                            hash_table: &mut ChainedHashTable,
+                                                            writer: &mut W,
+                                                            rolling_checksum: &mut RC,
+                                                            options: &CompressionOptions) {
     let end = cmp::min(data.len(), end);
     let current_chunk = &data[start..end];
     let mut insert_it = current_chunk.iter().enumerate();
@@ -207,13 +416,25 @@ fn process_chunk<W: OutputWriter, RC: RollingChecksum>(data: &[u8],
         if let Some(&hash_byte) = hash_it.next() {
             let position = n + start;
             hash_table.add_hash_value(position, hash_byte);
-            // rolling_checksum.update(hash_byte);
+            // Each byte that reaches this point is fed into the checksum here, whether it ends up
+            // written out as a literal or as part of a match; the bytes a match skips over are
+            // fed in by `advance_match`, and the last two bytes in the branch below.
+            rolling_checksum.update(b);
 
             // TODO: Currently, we only check for matches up to the end of the chunk, but ideally
             // we should be checking max_match bytes further to achieve the best possible
             // compression.
-            let (match_len, match_dist) =
-                longest_match(&data[..end], hash_table, position, prev_length);
+            // If we already have a match that is long enough, there is little to gain from
+            // checking whether the next position has a longer one, so we skip the lookup.
+            let (match_len, match_dist) = if prev_length >= options.lazy_if_less_than as usize {
+                (0, 0)
+            } else {
+                longest_match(&data[..end],
+                              hash_table,
+                              position,
+                              prev_length,
+                              options.max_hash_checks)
+            };
 
             if prev_length >= match_len && prev_length >= MIN_MATCH as usize {
                 // The previous match was better so we add it
@@ -226,16 +447,8 @@ fn process_chunk<W: OutputWriter, RC: RollingChecksum>(data: &[u8],
                 // the length
                 let bytes_to_add = prev_length - 2;
                 let taker = insert_it.by_ref().take(bytes_to_add);
-                let mut hash_taker = hash_it.by_ref().take(bytes_to_add);
-
-                // Advance the iterators and add the bytes we jump over to the hash table and
-                // checksum
-                for (ipos, _) in taker {
-                    if let Some(&i_hash_byte) = hash_taker.next() {
-                        // rolling_checksum.update(i_hash_byte);
-                        hash_table.add_hash_value(ipos + start, i_hash_byte);
-                    }
-                }
+                let hash_taker = hash_it.by_ref().take(bytes_to_add);
+                advance_match(taker, hash_taker, start, hash_table, rolling_checksum, |_| {});
 
                 add = false;
 
@@ -258,6 +471,7 @@ fn process_chunk<W: OutputWriter, RC: RollingChecksum>(data: &[u8],
             }
             // We are at the last two bytes we want to add, so there is no point
             // searching for matches here.
+            rolling_checksum.update(b);
             writer.write_literal(b);
         }
     }
@@ -267,13 +481,137 @@ fn process_chunk<W: OutputWriter, RC: RollingChecksum>(data: &[u8],
     }
 }
 
+fn process_chunk_greedy<W: OutputWriter, RC: RollingChecksum>(data: &[u8],
+                                                              start: usize,
+                                                              end: usize,
+                                                              hash_table: &mut ChainedHashTable,
+                                                              writer: &mut W,
+                                                              rolling_checksum: &mut RC,
+                                                              options: &CompressionOptions) {
+    let end = cmp::min(data.len(), end);
+    let current_chunk = &data[start..end];
+    let mut insert_it = current_chunk.iter().enumerate();
+    let mut hash_it = (&data[start + 2..]).iter();
+
+    const NO_LENGTH: usize = MIN_MATCH as usize - 1;
+
+    // Accelerate over long runs of positions that don't produce a match (as happens on
+    // already-compressed or encrypted input), similarly to lz4_flex's skip-ahead logic: once
+    // `skip_count` positions in a row have found nothing, advance the insert position by
+    // `skip_count >> STEP_BITSHIFT` extra bytes instead of just one, still lazily adding the
+    // hash values of the bytes jumped over (without checking them for matches), and reset
+    // back to normal the moment a match is found.
+    const STEP_BITSHIFT: usize = 6;
+    let mut skip_count: usize = 1;
+
+    // Iterate through the slice, adding literals or length/distance pairs, emitting a match
+    // as soon as one is found rather than deferring to check the next position.
+    while let Some((n, &b)) = insert_it.next() {
+        if let Some(&hash_byte) = hash_it.next() {
+            let position = n + start;
+            hash_table.add_hash_value(position, hash_byte);
+            // Each byte that reaches this point is fed into the checksum here, whether it ends up
+            // written out as a literal or as part of a match; the bytes a match or a skip-ahead
+            // step jumps over are fed in by `advance_match`, and the last two bytes in the
+            // branch below.
+            rolling_checksum.update(b);
+
+            let (match_len, match_dist) = longest_match(&data[..end],
+                                                         hash_table,
+                                                         position,
+                                                         NO_LENGTH,
+                                                         options.max_hash_checks);
+
+            if match_len >= MIN_MATCH as usize {
+                // Casting note: length and distance is already bounded by the longest match
+                // function. Usize is just used for convenience
+                writer.write_length_distance(match_len as u16, match_dist as u16);
+
+                // We've already added the first byte of the match to the hash table, so we
+                // only need to add the rest.
+                let bytes_to_add = match_len - 1;
+                let taker = insert_it.by_ref().take(bytes_to_add);
+                let hash_taker = hash_it.by_ref().take(bytes_to_add);
+                advance_match(taker, hash_taker, start, hash_table, rolling_checksum, |_| {});
+
+                skip_count = 1;
+            } else {
+                writer.write_literal(b);
+
+                let step = skip_count >> STEP_BITSHIFT;
+                if step > 0 {
+                    let taker = insert_it.by_ref().take(step);
+                    let hash_taker = hash_it.by_ref().take(step);
+                    advance_match(taker,
+                                 hash_taker,
+                                 start,
+                                 hash_table,
+                                 rolling_checksum,
+                                 |b| writer.write_literal(b));
+                }
+                skip_count += 1;
+            }
+        } else {
+            // We are at the last two bytes we want to add, so there is no point
+            // searching for matches here.
+            rolling_checksum.update(b);
+            writer.write_literal(b);
+        }
+    }
+}
+
+/// A run-length-only version of greedy matching, which only looks for matches at distance 1
+/// (i.e. runs of a repeated byte) and doesn't touch the hash chain at all. This is much faster
+/// than chain-walking matching, at the cost of compression ratio on data that isn't just runs
+/// of repeated bytes.
+fn process_chunk_greedy_rle<W: OutputWriter, RC: RollingChecksum>(data: &[u8],
+                                                                  start: usize,
+                                                                  end: usize,
+                                                                  writer: &mut W,
+                                                                  rolling_checksum: &mut RC) {
+    let end = cmp::min(data.len(), end);
+    let mut n = start;
+    while n < end {
+        let byte = data[n];
+
+        // A distance-1 match reproduces the byte immediately preceding it on decompression, not
+        // `byte` itself, so it's only a correct encoding of this run when that previous byte is
+        // also part of it. There is no previous byte at all at the very start of the stream
+        // (n == 0 only ever occurs there, since later windows start at `window_size`), so that
+        // byte is always emitted as a literal too.
+        if n > 0 && data[n - 1] == byte {
+            // Bounded by `end`, not `data.len()`, so a run that starts near the end of this
+            // chunk doesn't keep scanning (and emitting as part of this match) bytes belonging
+            // to the next window, which would then be re-emitted when that window is processed.
+            let max_length = cmp::min(end - n, MAX_MATCH);
+
+            let mut run_length = 1;
+            while run_length < max_length && data[n + run_length] == byte {
+                run_length += 1;
+            }
+
+            if run_length >= MIN_MATCH {
+                rolling_checksum.update_from_slice(&data[n..n + run_length]);
+                writer.write_length_distance(run_length as u16, 1);
+                n += run_length;
+                continue;
+            }
+        }
+
+        rolling_checksum.update(byte);
+        writer.write_literal(byte);
+        n += 1;
+    }
+}
+
 /// Compress a slice
 /// Will return err on failure eventually, but for now allways succeeds or panics
 pub fn lz77_compress_block<W: OutputWriter, RC: RollingChecksum>(data: &[u8],
                                                                  state: &mut LZ77State,
                                                                  buffer: &mut [u8],
                                                                  mut writer: &mut W,
-                                                                 mut rolling_checksum: &mut RC)
+                                                                 mut rolling_checksum: &mut RC,
+                                                                 options: &CompressionOptions)
                                                                  -> Option<bool> {
     // Currently we use window size as block length, in the future we might want to allow
     // differently sized blocks
@@ -297,7 +635,8 @@ pub fn lz77_compress_block<W: OutputWriter, RC: RollingChecksum>(data: &[u8],
                                first_chunk_end,
                                &mut state.hash_table,
                                &mut writer,
-                               &mut rolling_checksum);
+                               &mut rolling_checksum,
+                               options);
         // We are at the first block so we don't need to slide the hash table
         state.current_start += first_chunk_end;
         if first_chunk_end >= data.len() {
@@ -314,7 +653,8 @@ pub fn lz77_compress_block<W: OutputWriter, RC: RollingChecksum>(data: &[u8],
                                    end,
                                    &mut state.hash_table,
                                    &mut writer,
-                                   &mut rolling_checksum);
+                                   &mut rolling_checksum,
+                                   options);
             if end >= slice.len() {
                 state.set_last();
             } else {
@@ -325,7 +665,8 @@ pub fn lz77_compress_block<W: OutputWriter, RC: RollingChecksum>(data: &[u8],
                 // deflate spec)
                 state.hash_table.slide(window_size);
                 let end = cmp::min(start + window_size + 2, data.len());
-                //                rolling_checksum.update_from_slice(&data[start + 2..end]);
+                // The checksum is updated per-byte inside `process_chunk` itself as bytes are
+                // consumed, so there's nothing to do here beyond sliding the buffer.
                 slide_buffer(buffer, &data[start..end]);
             }
 
@@ -340,6 +681,91 @@ pub fn lz77_compress_block<W: OutputWriter, RC: RollingChecksum>(data: &[u8],
     Some(true)
 }
 
+/// Controls how much of the currently buffered input is flushed out as output, mirroring the
+/// flush modes used by zlib and other deflate implementations.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Flush {
+    /// Don't force anything out; only process data once more than a full window is buffered.
+    None,
+    /// Flush out all currently buffered data as a block boundary, without marking the stream
+    /// as finished, so more data can still be fed in afterwards.
+    Sync,
+    /// Flush out all currently buffered data and mark the last block as the final one.
+    Finish,
+}
+
+/// Compress as much of the data currently held in `input_buffer` as possible.
+///
+/// Unlike `lz77_compress_block`, this doesn't require the full input up front; it processes
+/// as many full windows as the buffered data allows, and only goes on to compress a shorter,
+/// partial window when `flush` is `Flush::Sync` or `Flush::Finish`. This lets a caller feed
+/// data in arbitrary chunks, for instance when wrapping an `io::Write`. `state.is_last_block`
+/// is only ever set when `flush` is `Flush::Finish`.
+pub fn lz77_compress_block_stream<W: OutputWriter, RC: RollingChecksum>(input_buffer: &mut InputBuffer,
+                                                                        state: &mut LZ77State,
+                                                                        mut writer: &mut W,
+                                                                        mut rolling_checksum: &mut RC,
+                                                                        options: &CompressionOptions,
+                                                                        flush: Flush)
+                                                                        -> bool {
+    let window_size = DEFAULT_WINDOW_SIZE;
+
+    loop {
+        let window_start = if state.is_first_window { 0 } else { window_size };
+        let full_window_end = window_start + window_size;
+        let buffered = input_buffer.current_end();
+
+        if buffered > full_window_end {
+            // We have more than a full window buffered, so we know this isn't the last chunk
+            // of data and can process a full window right away.
+            process_chunk::<W, RC>(input_buffer.get_buffer(),
+                                   window_start,
+                                   full_window_end,
+                                   &mut state.hash_table,
+                                   &mut writer,
+                                   &mut rolling_checksum,
+                                   options);
+            writer.write_end_of_block();
+
+            if state.is_first_window {
+                state.current_start = full_window_end;
+                state.is_first_window = false;
+            } else {
+                state.current_start += window_size;
+                // We slide the hash table back to make space for new hash values.
+                // We only need to remember 32k bytes back (the maximum distance allowed by
+                // the deflate spec)
+                state.hash_table.slide(window_size);
+                input_buffer.slide(window_size);
+            }
+        } else if flush != Flush::None && buffered > window_start {
+            // We don't have a full window, but we've been asked to flush what we have out as
+            // a block boundary.
+            process_chunk::<W, RC>(input_buffer.get_buffer(),
+                                   window_start,
+                                   buffered,
+                                   &mut state.hash_table,
+                                   &mut writer,
+                                   &mut rolling_checksum,
+                                   options);
+            writer.write_end_of_block();
+            state.current_start = buffered;
+            state.is_first_window = false;
+
+            if flush == Flush::Finish {
+                state.set_last();
+            }
+            break;
+        } else {
+            // Not enough data buffered for a full window, and we haven't been asked to flush,
+            // so wait for more data to be added to `input_buffer`.
+            break;
+        }
+    }
+
+    state.is_last_block()
+}
+
 /// Compress a slice, not storing frequency information
 ///
 /// This is a convenience function for compression with fixed huffman values
@@ -351,8 +777,14 @@ pub fn lz77_compress(data: &[u8]) -> Option<Vec<LDPair>> {
     let mut state = LZ77State::new(data);
     let mut dummy_checksum = NoChecksum::new();
     let mut buffer = create_buffer(data);
+    let options = CompressionOptions::default();
     while !state.is_last_block {
-        lz77_compress_block(data, &mut state, &mut buffer, &mut w, &mut dummy_checksum);
+        lz77_compress_block(data,
+                            &mut state,
+                            &mut buffer,
+                            &mut w,
+                            &mut dummy_checksum,
+                            &options);
     }
     Some(w.buffer)
 }
@@ -395,6 +827,28 @@ mod test {
         assert_eq!(l3, 4);
     }
 
+    /// Test that the word-at-a-time match length matches the safe byte-by-byte fallback,
+    /// including around the edges of the input slice.
+    #[test]
+    fn test_match_length_word_matches_fallback() {
+        use std::fs::File;
+        use std::io::Read;
+
+        let mut data = Vec::new();
+        let mut f = File::open("tests/pg11.txt").unwrap();
+        f.read_to_end(&mut data).unwrap();
+
+        for &(current_pos, pos_to_check) in &[(10, 0),
+                                               (1000, 3),
+                                               (data.len() - 9, 0),
+                                               (data.len() - 1, 0),
+                                               (500, 499)] {
+            let word = super::get_match_length(&data, current_pos, pos_to_check);
+            let fallback = super::get_match_length_fallback(&data, current_pos, pos_to_check);
+            assert_eq!(word, fallback);
+        }
+    }
+
     /// Test that we get the longest of the matches
     #[test]
     fn test_longest_match() {
@@ -484,6 +938,48 @@ mod test {
         assert!(&decompressed == &input);
     }
 
+    /// A simple, non-incremental reference implementation of the Adler-32 checksum, to check
+    /// the rolling checksum fed through the LZ77 pass against.
+    fn adler32_reference(data: &[u8]) -> u32 {
+        const MOD_ADLER: u32 = 65521;
+        let mut a: u32 = 1;
+        let mut b: u32 = 0;
+        for &byte in data {
+            a = (a + byte as u32) % MOD_ADLER;
+            b = (b + a) % MOD_ADLER;
+        }
+        (b << 16) | a
+    }
+
+    /// Check that the Adler-32 checksum accumulated while running the LZ77 pass over a longer
+    /// file matches a reference implementation run over the same input.
+    #[test]
+    fn test_lz77_checksum() {
+        use checksum::Adler32Checksum;
+        use std::fs::File;
+        use std::io::Read;
+
+        let mut input = Vec::new();
+        let mut f = File::open("tests/pg11.txt").unwrap();
+        f.read_to_end(&mut input).unwrap();
+
+        let mut w = FixedWriter::new();
+        let mut state = LZ77State::new(&input);
+        let mut checksum = Adler32Checksum::new();
+        let mut buffer = create_buffer(&input);
+        let options = CompressionOptions::default();
+        while !state.is_last_block {
+            lz77_compress_block(&input,
+                                &mut state,
+                                &mut buffer,
+                                &mut w,
+                                &mut checksum,
+                                &options);
+        }
+
+        assert_eq!(checksum.current_hash(), adler32_reference(&input));
+    }
+
     /// Check that lazy matching is working as intended
     #[test]
     fn test_lazy() {
@@ -520,4 +1016,132 @@ mod test {
         let decompressed = decompress_lz77(&compressed);
         assert!(decompressed == data);
     }
+
+    /// Check that the streaming API round-trips data fed in over several `add_data`/
+    /// `lz77_compress_block_stream` calls, using a chunk size that doesn't line up with window
+    /// boundaries, so `add_data` sometimes can't take a whole chunk and `slide` has to make
+    /// room for the rest. This is the path that `InputBuffer::slide`'s overlapping-range bug
+    /// reproduced on.
+    #[test]
+    fn test_streaming_roundtrip() {
+        use chained_hash_table::WINDOW_SIZE;
+        use checksum::NoChecksum;
+
+        let data: Vec<u8> = (0..(WINDOW_SIZE * 3 + 1000) as u32)
+            .map(|n| (n.wrapping_mul(2654435761) >> 7) as u8)
+            .collect();
+
+        let options = CompressionOptions::default();
+        let mut w = FixedWriter::new();
+        let mut input_buffer = InputBuffer::empty();
+        let mut state = LZ77State::new(&data);
+        let mut dummy_checksum = NoChecksum::new();
+
+        const CHUNK_SIZE: usize = 4001;
+        let mut offset = 0;
+        let mut is_last = false;
+        // One iteration per chunk fed in is enough to drain the buffer in between, so this
+        // comfortably bounds how many times the loop should need to run; if it's still not
+        // done by then, something is stuck rather than just slow.
+        for _ in 0..(data.len() / CHUNK_SIZE + 10) {
+            if !is_last {
+                let end = cmp::min(offset + CHUNK_SIZE, data.len());
+                offset += input_buffer.add_data(&data[offset..end]);
+            }
+            let flush = if offset >= data.len() { Flush::Finish } else { Flush::None };
+            is_last = lz77_compress_block_stream(&mut input_buffer,
+                                                 &mut state,
+                                                 &mut w,
+                                                 &mut dummy_checksum,
+                                                 &options,
+                                                 flush);
+            if is_last {
+                break;
+            }
+        }
+        assert!(is_last, "streaming compression never reached the last block");
+
+        let decompressed = decompress_lz77(&w.buffer);
+        assert_eq!(data, decompressed);
+    }
+
+    /// Check that RLE-only matching (`max_hash_checks: 0`) roundtrips correctly on a run of
+    /// repeated bytes spanning more than one window, where a run starting close to the window
+    /// boundary must not be allowed to scan past it.
+    #[test]
+    fn test_greedy_rle_roundtrip_multiple_blocks() {
+        use chained_hash_table::WINDOW_SIZE;
+        use checksum::NoChecksum;
+
+        let data = vec![0u8; (WINDOW_SIZE * 2) + 50];
+
+        let options = CompressionOptions::new(MatchingType::Greedy, 0, 128);
+        let mut w = FixedWriter::new();
+        let mut state = LZ77State::new(&data);
+        let mut dummy_checksum = NoChecksum::new();
+        let mut buffer = create_buffer(&data);
+        while !state.is_last_block {
+            lz77_compress_block(&data,
+                                &mut state,
+                                &mut buffer,
+                                &mut w,
+                                &mut dummy_checksum,
+                                &options);
+        }
+        let decompressed = decompress_lz77(&w.buffer);
+        assert_eq!(data, decompressed);
+    }
+
+    /// Check that a short run of a repeated byte that differs from the byte right before it is
+    /// still roundtripped correctly in RLE mode. A distance-1 match reproduces the *previous*
+    /// output byte, not the run's own byte value, so a run can only be encoded that way when the
+    /// byte before it also matches; otherwise it has to fall back to literals.
+    #[test]
+    fn test_greedy_rle_roundtrip_short_run() {
+        use checksum::NoChecksum;
+
+        let data = b"xxxxxABBBAxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx".to_vec();
+
+        let options = CompressionOptions::new(MatchingType::Greedy, 0, 128);
+        let mut w = FixedWriter::new();
+        let mut state = LZ77State::new(&data);
+        let mut dummy_checksum = NoChecksum::new();
+        let mut buffer = create_buffer(&data);
+        while !state.is_last_block {
+            lz77_compress_block(&data,
+                                &mut state,
+                                &mut buffer,
+                                &mut w,
+                                &mut dummy_checksum,
+                                &options);
+        }
+        let decompressed = decompress_lz77(&w.buffer);
+        assert_eq!(data, decompressed);
+    }
+
+    /// Check that greedy matching with the skip-ahead acceleration still roundtrips correctly.
+    /// The data repeats every 256 bytes, but its first 256 bytes are all distinct, so no match
+    /// is found for long enough there that the skip-ahead logic kicks in before matches begin.
+    #[test]
+    fn test_greedy_skip_ahead_roundtrip() {
+        use checksum::NoChecksum;
+
+        let data: Vec<u8> = (0..50000u32).map(|n| (n.wrapping_mul(2654435761)) as u8).collect();
+
+        let options = CompressionOptions::new(MatchingType::Greedy, 4096, 128);
+        let mut w = FixedWriter::new();
+        let mut state = LZ77State::new(&data);
+        let mut dummy_checksum = NoChecksum::new();
+        let mut buffer = create_buffer(&data);
+        while !state.is_last_block {
+            lz77_compress_block(&data,
+                                &mut state,
+                                &mut buffer,
+                                &mut w,
+                                &mut dummy_checksum,
+                                &options);
+        }
+        let decompressed = decompress_lz77(&w.buffer);
+        assert_eq!(data, decompressed);
+    }
 }